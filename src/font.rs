@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font as AbFont, FontArc, InvalidFont, ScaleFont};
+
+use crate::graphics::Vertex;
+
+struct GlyphInfo {
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    size: [f32; 2],
+    bearing: [f32; 2],
+    advance: f32,
+}
+
+/// A loaded font with a growing glyph atlas, rasterized on demand as new
+/// characters are printed.
+pub struct Font {
+    font: FontArc,
+    scale: f32,
+    atlas_width: u32,
+    atlas_height: u32,
+    atlas: Vec<u8>,
+    cursor_x: u32,
+    cursor_y: u32,
+    row_height: u32,
+    glyphs: HashMap<char, GlyphInfo>,
+    dirty: bool,
+}
+
+impl Font {
+    pub fn new(bytes: Vec<u8>, scale: f32) -> Result<Font, InvalidFont> {
+        let font = FontArc::try_from_vec(bytes)?;
+
+        Ok(Font {
+            font,
+            scale,
+            atlas_width: 512,
+            atlas_height: 512,
+            atlas: vec![0_u8; 512 * 512],
+            cursor_x: 0,
+            cursor_y: 0,
+            row_height: 0,
+            glyphs: HashMap::new(),
+            dirty: false,
+        })
+    }
+
+    pub fn atlas_size(&self) -> (u32, u32) {
+        (self.atlas_width, self.atlas_height)
+    }
+
+    pub fn atlas_pixels(&self) -> &[u8] {
+        &self.atlas
+    }
+
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    pub fn line_height(&self) -> f32 {
+        let scaled = self.font.as_scaled(self.scale);
+        scaled.height() + scaled.line_gap()
+    }
+
+    fn rasterize(&mut self, c: char) {
+        if self.glyphs.contains_key(&c) {
+            return;
+        }
+
+        let glyph_id = self.font.glyph_id(c);
+        let scaled = self.font.as_scaled(self.scale);
+        let advance = scaled.h_advance(glyph_id);
+        let glyph = glyph_id.with_scale_and_position(self.scale, ab_glyph::point(0_f32, 0_f32));
+
+        let info = match self.font.outline_glyph(glyph) {
+            Some(outlined) => {
+                let bounds = outlined.px_bounds();
+                let width = bounds.width().ceil() as u32;
+                let height = bounds.height().ceil() as u32;
+
+                if width > self.atlas_width {
+                    self.grow_atlas_width(width);
+                }
+
+                if self.cursor_x + width > self.atlas_width {
+                    self.cursor_x = 0;
+                    self.cursor_y += self.row_height;
+                    self.row_height = 0;
+                }
+                self.row_height = self.row_height.max(height);
+
+                if self.cursor_y + self.row_height > self.atlas_height {
+                    self.grow_atlas_height();
+                }
+
+                let origin_x = self.cursor_x;
+                let origin_y = self.cursor_y;
+
+                let atlas_width = self.atlas_width;
+                let atlas = &mut self.atlas;
+                outlined.draw(|x, y, coverage| {
+                    let index = ((origin_y + y) * atlas_width + (origin_x + x)) as usize;
+                    atlas[index] = (coverage * 255_f32) as u8;
+                });
+
+                self.cursor_x += width;
+                self.dirty = true;
+
+                GlyphInfo {
+                    uv_min: [
+                        origin_x as f32 / self.atlas_width as f32,
+                        origin_y as f32 / self.atlas_height as f32,
+                    ],
+                    uv_max: [
+                        (origin_x + width) as f32 / self.atlas_width as f32,
+                        (origin_y + height) as f32 / self.atlas_height as f32,
+                    ],
+                    size: [width as f32, height as f32],
+                    bearing: [bounds.min.x, bounds.min.y],
+                    advance,
+                }
+            }
+            None => GlyphInfo {
+                uv_min: [0_f32, 0_f32],
+                uv_max: [0_f32, 0_f32],
+                size: [0_f32, 0_f32],
+                bearing: [0_f32, 0_f32],
+                advance,
+            },
+        };
+
+        self.glyphs.insert(c, info);
+    }
+
+    /// Doubles the atlas height, preserving already-rasterized rows and
+    /// re-normalizing their stored UVs against the new height.
+    fn grow_atlas_height(&mut self) {
+        let old_height = self.atlas_height;
+        let new_height = old_height * 2;
+
+        let mut atlas = vec![0_u8; (self.atlas_width * new_height) as usize];
+        atlas[..self.atlas.len()].copy_from_slice(&self.atlas);
+        self.atlas = atlas;
+        self.atlas_height = new_height;
+
+        let scale = old_height as f32 / new_height as f32;
+        for info in self.glyphs.values_mut() {
+            info.uv_min[1] *= scale;
+            info.uv_max[1] *= scale;
+        }
+    }
+
+    /// Doubles the atlas width (repeatedly, if needed) until it can fit a
+    /// glyph `min_width` pixels wide, re-striding already-rasterized rows
+    /// and re-normalizing their stored UVs against the new width.
+    fn grow_atlas_width(&mut self, min_width: u32) {
+        let old_width = self.atlas_width;
+        let mut new_width = old_width;
+        while new_width < min_width {
+            new_width *= 2;
+        }
+
+        let mut atlas = vec![0_u8; (new_width * self.atlas_height) as usize];
+        for y in 0..self.atlas_height {
+            let old_start = (y * old_width) as usize;
+            let new_start = (y * new_width) as usize;
+            atlas[new_start..new_start + old_width as usize]
+                .copy_from_slice(&self.atlas[old_start..old_start + old_width as usize]);
+        }
+        self.atlas = atlas;
+        self.atlas_width = new_width;
+
+        let scale = old_width as f32 / new_width as f32;
+        for info in self.glyphs.values_mut() {
+            info.uv_min[0] *= scale;
+            info.uv_max[0] *= scale;
+        }
+    }
+
+    fn measure(&mut self, text: &str) -> f32 {
+        let mut width = 0_f32;
+        for c in text.chars() {
+            self.rasterize(c);
+            width += self.glyphs.get(&c).unwrap().advance;
+        }
+        width
+    }
+
+    /// Lays out `text` starting at `(x, y)`, emitting one textured quad per
+    /// visible glyph. `y` is the top-left pixel origin the rest of
+    /// `Graphics` draws in, i.e. the top of the text, not the baseline.
+    pub fn layout(&mut self, text: &str, x: f32, y: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut pen_x = x;
+        let baseline = y + self.font.as_scaled(self.scale).ascent();
+
+        for c in text.chars() {
+            self.rasterize(c);
+            let info = self.glyphs.get(&c).unwrap();
+
+            if info.size[0] > 0_f32 && info.size[1] > 0_f32 {
+                let start = vertices.len() as u32;
+                let gx = pen_x + info.bearing[0];
+                let gy = baseline + info.bearing[1];
+
+                vertices.push(Vertex {
+                    position: [gx, gy],
+                    uv: [info.uv_min[0], info.uv_min[1]],
+                });
+                vertices.push(Vertex {
+                    position: [gx, gy + info.size[1]],
+                    uv: [info.uv_min[0], info.uv_max[1]],
+                });
+                vertices.push(Vertex {
+                    position: [gx + info.size[0], gy],
+                    uv: [info.uv_max[0], info.uv_min[1]],
+                });
+                vertices.push(Vertex {
+                    position: [gx + info.size[0], gy + info.size[1]],
+                    uv: [info.uv_max[0], info.uv_max[1]],
+                });
+
+                indices.push(start);
+                indices.push(start + 1);
+                indices.push(start + 2);
+                indices.push(start + 2);
+                indices.push(start + 1);
+                indices.push(start + 3);
+            }
+
+            pen_x += info.advance;
+        }
+
+        (vertices, indices)
+    }
+
+    /// Splits `text` into lines that each fit within `wrap_width`, breaking
+    /// on whitespace, for `Graphics::printf`. Words are never split: a word
+    /// wider than `wrap_width` gets a line of its own that exceeds it.
+    pub fn wrap(&mut self, text: &str, wrap_width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+        let mut current_width = 0_f32;
+        let space_width = self.measure(" ");
+
+        for word in text.split_whitespace() {
+            let word_width = self.measure(word);
+
+            if !current.is_empty() && current_width + space_width + word_width > wrap_width {
+                lines.push(std::mem::take(&mut current));
+                current_width = 0_f32;
+            }
+
+            if !current.is_empty() {
+                current.push(' ');
+                current_width += space_width;
+            }
+            current.push_str(word);
+            current_width += word_width;
+        }
+
+        if !current.is_empty() {
+            lines.push(current);
+        }
+
+        lines
+    }
+}
@@ -1,7 +1,19 @@
+use std::collections::HashMap;
 use std::ops::Mul;
 
+use lyon_tessellation::math::{point, Point};
+use lyon_tessellation::path::{Path, Polygon, Winding};
+use lyon_tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, FillVertexConstructor,
+    StrokeOptions, StrokeTessellator, StrokeVertex, StrokeVertexConstructor, TessellationError,
+    VertexBuffers,
+};
+
+use crate::font::Font;
+use crate::shader::{PreprocessError, ShaderRegistry};
+
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
@@ -10,7 +22,7 @@ pub struct Color {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Matrix4 {
     pub data: [f32; 16],
 }
@@ -60,6 +72,42 @@ impl Matrix4 {
         }
     }
 
+    #[rustfmt::skip]
+    pub fn scale(sx: f32, sy: f32) -> Matrix4 {
+        Matrix4 {
+            data: [
+                sx,    0_f32, 0_f32, 0_f32,
+                0_f32, sy,    0_f32, 0_f32,
+                0_f32, 0_f32, 1_f32, 0_f32,
+                0_f32, 0_f32, 0_f32, 1_f32,
+            ]
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn shear(x: f32, y: f32) -> Matrix4 {
+        Matrix4 {
+            data: [
+                1_f32, x,     0_f32, 0_f32,
+                y,     1_f32, 0_f32, 0_f32,
+                0_f32, 0_f32, 1_f32, 0_f32,
+                0_f32, 0_f32, 0_f32, 1_f32,
+            ]
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Matrix4 {
+        Matrix4 {
+            data: [
+                2_f32 / (right - left), 0_f32,                  0_f32,                 -(right + left) / (right - left),
+                0_f32,                  2_f32 / (top - bottom),  0_f32,                 -(top + bottom) / (top - bottom),
+                0_f32,                  0_f32,                  -2_f32 / (far - near),  -(far + near) / (far - near),
+                0_f32,                  0_f32,                   0_f32,                  1_f32,
+            ]
+        }
+    }
+
     pub fn transposed(&self) -> Matrix4 {
         let mut data = [0_f32; 16];
 
@@ -98,17 +146,82 @@ impl Mul for Matrix4 {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 2],
+    pub uv: [f32; 2],
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct PushValues {
-    projection: Matrix4,
-    transformation: Matrix4,
-    color: Color,
+    pub projection: Matrix4,
+    pub transformation: Matrix4,
+    /// `Color` downcast to `f32`s, matching the `vec4<f32>` the WGSL push
+    /// constant block declares — the GPU side has no `f64`.
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextureHandle(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FontHandle(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ImageHandle(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShaderHandle(pub usize);
+
+/// Size of the uniform buffer a backend allocates per custom shader. Kept
+/// here, alongside the offsets `Graphics::send` hands out, so the two stay
+/// in lockstep instead of drifting apart as separate constants.
+pub const UNIFORM_BUFFER_SIZE: u32 = 256;
+
+/// Raised by `Graphics::send` when a shader has already claimed every slot
+/// in its uniform buffer.
+#[derive(Debug)]
+pub struct UniformBufferOverflow {
+    pub name: String,
+}
+
+impl std::fmt::Display for UniformBufferOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "uniform buffer is full, cannot send \"{}\" (buffer holds {} bytes)",
+            self.name, UNIFORM_BUFFER_SIZE
+        )
+    }
+}
+
+/// A value passed to a custom shader's uniform buffer via `Graphics::send`.
+///
+/// Each variant is padded to 16 bytes, and `send` hands out offsets in
+/// first-call order: the first never-seen-before name gets offset 0, the
+/// next gets 16, and so on. The custom shader's WGSL uniform struct must
+/// declare its fields in that same order, each sized to a 16-byte stride
+/// (e.g. `vec4<f32>`), for `send`'s offsets to land on the right fields.
+#[derive(Clone, Copy)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec4([f32; 4]),
+}
+
+impl UniformValue {
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0_u8; 16];
+
+        match self {
+            UniformValue::Float(v) => bytes[0..4].copy_from_slice(&v.to_le_bytes()),
+            UniformValue::Vec2(v) => bytes[0..8].copy_from_slice(bytemuck::bytes_of(&v)),
+            UniformValue::Vec4(v) => bytes[0..16].copy_from_slice(bytemuck::bytes_of(&v)),
+        }
+
+        bytes
+    }
 }
 
 #[derive(Clone)]
@@ -116,12 +229,78 @@ pub struct DrawCommand {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub push_values: PushValues,
+    pub texture: Option<TextureHandle>,
+    pub shader: Option<ShaderHandle>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    Fill,
+    Line,
+}
+
+impl DrawMode {
+    pub fn from_str(mode: &str) -> DrawMode {
+        match mode {
+            "line" => DrawMode::Line,
+            _ => DrawMode::Fill,
+        }
+    }
+}
+
+struct VertexCtor;
+
+impl FillVertexConstructor<Vertex> for VertexCtor {
+    fn new_vertex(&mut self, vertex: FillVertex) -> Vertex {
+        Vertex {
+            position: vertex.position().to_array(),
+            uv: [0_f32, 0_f32],
+        }
+    }
+}
+
+impl StrokeVertexConstructor<Vertex> for VertexCtor {
+    fn new_vertex(&mut self, vertex: StrokeVertex) -> Vertex {
+        Vertex {
+            position: vertex.position().to_array(),
+            uv: [0_f32, 0_f32],
+        }
+    }
+}
+
+/// Pixel layout of a texture's backing data, enough for the backend to pick
+/// the right GPU format and row stride.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    /// One byte per pixel, coverage only. Used by the glyph atlas.
+    R8,
+    /// Four bytes per pixel, RGBA. Used by loaded images.
+    Rgba8,
 }
 
 pub trait GraphicsBackend {
     fn request_swapchain_recreation(&mut self, new_width: u32, new_height: u32);
     fn set_clear_color(&mut self, r: f64, g: f64, b: f64, a: f64);
     fn present(&mut self, draw_commands: Vec<DrawCommand>);
+    fn create_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        pixels: &[u8],
+    ) -> TextureHandle;
+    fn update_texture(
+        &mut self,
+        texture: TextureHandle,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    );
+    fn destroy_texture(&mut self, texture: TextureHandle);
+    fn create_shader(&mut self, vertex_source: Option<&str>, fragment_source: &str) -> ShaderHandle;
+    fn send_uniform(&mut self, shader: ShaderHandle, offset: u32, bytes: &[u8]);
 }
 
 pub struct Graphics<T: GraphicsBackend> {
@@ -131,10 +310,23 @@ pub struct Graphics<T: GraphicsBackend> {
     transformation_stack: Vec<Matrix4>,
     draw_commands: Vec<DrawCommand>,
     color: Color,
+    width: u32,
+    height: u32,
+    line_width: f32,
+    fill_tessellator: FillTessellator,
+    stroke_tessellator: StrokeTessellator,
+    pending_texture: Option<TextureHandle>,
+    fonts: Vec<Font>,
+    font_textures: HashMap<usize, (TextureHandle, u32, u32)>,
+    current_font: Option<FontHandle>,
+    images: Vec<(TextureHandle, u32, u32)>,
+    shader_registry: ShaderRegistry,
+    shader_uniforms: Vec<(HashMap<String, u32>, u32)>,
+    current_shader: Option<ShaderHandle>,
 }
 
 impl<T: GraphicsBackend> Graphics<T> {
-    pub fn new(graphics_backend: T) -> Graphics<T> {
+    pub fn new(graphics_backend: T, width: u32, height: u32) -> Graphics<T> {
         let transformation_stack = vec![Matrix4::identity()];
 
         Graphics {
@@ -144,6 +336,72 @@ impl<T: GraphicsBackend> Graphics<T> {
             transformation_stack,
             draw_commands: Vec::new(),
             color: Color { r: 1_f64, g: 1_f64, b: 1_f64, a: 1_f64 },
+            width,
+            height,
+            line_width: 1_f32,
+            fill_tessellator: FillTessellator::new(),
+            stroke_tessellator: StrokeTessellator::new(),
+            pending_texture: None,
+            fonts: Vec::new(),
+            font_textures: HashMap::new(),
+            current_font: None,
+            images: Vec::new(),
+            shader_registry: ShaderRegistry::new(),
+            shader_uniforms: Vec::new(),
+            current_shader: None,
+        }
+    }
+
+    pub fn set_line_width(&mut self, width: f32) {
+        self.line_width = width;
+    }
+
+    fn append_geometry(&mut self, geometry: VertexBuffers<Vertex, u32>) {
+        let offset = self.vertices.len() as u32;
+
+        self.vertices.extend(geometry.vertices);
+        self.indices
+            .extend(geometry.indices.into_iter().map(|index| index + offset));
+    }
+
+    fn tessellate_fill(
+        &mut self,
+        path: &Path,
+    ) -> Result<VertexBuffers<Vertex, u32>, TessellationError> {
+        let mut geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+
+        self.fill_tessellator.tessellate_path(
+            path,
+            &FillOptions::default(),
+            &mut BuffersBuilder::new(&mut geometry, VertexCtor),
+        )?;
+
+        Ok(geometry)
+    }
+
+    fn tessellate_stroke(
+        &mut self,
+        path: &Path,
+    ) -> Result<VertexBuffers<Vertex, u32>, TessellationError> {
+        let mut geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+
+        self.stroke_tessellator.tessellate_path(
+            path,
+            &StrokeOptions::default().with_line_width(self.line_width),
+            &mut BuffersBuilder::new(&mut geometry, VertexCtor),
+        )?;
+
+        Ok(geometry)
+    }
+
+    fn tessellate(
+        &mut self,
+        mode: DrawMode,
+        path: &Path,
+    ) -> Result<VertexBuffers<Vertex, u32>, TessellationError> {
+        match mode {
+            DrawMode::Fill => self.tessellate_fill(path),
+            DrawMode::Line => self.tessellate_stroke(path),
         }
     }
 
@@ -174,6 +432,33 @@ impl<T: GraphicsBackend> Graphics<T> {
         self.transformation_stack.push(current * Matrix4::rotation(r));
     }
 
+    pub fn scale(&mut self, sx: f32, sy: f32) {
+        self.flush_batched_draws();
+
+        let current = self.transformation_stack.pop().unwrap();
+        self.transformation_stack.push(current * Matrix4::scale(sx, sy));
+    }
+
+    pub fn shear(&mut self, x: f32, y: f32) {
+        self.flush_batched_draws();
+
+        let current = self.transformation_stack.pop().unwrap();
+        self.transformation_stack.push(current * Matrix4::shear(x, y));
+    }
+
+    pub fn push(&mut self) {
+        let current = *self.transformation_stack.last().unwrap();
+        self.transformation_stack.push(current);
+    }
+
+    pub fn pop(&mut self) {
+        self.flush_batched_draws();
+
+        if self.transformation_stack.len() > 1 {
+            self.transformation_stack.pop();
+        }
+    }
+
     pub fn flush_batched_draws(&mut self) {
         if self.vertices.len() > 0 {
             let indices = self.indices.clone();
@@ -183,23 +468,40 @@ impl<T: GraphicsBackend> Graphics<T> {
             self.indices.clear();
             self.vertices.clear();
 
+            let projection =
+                Matrix4::orthographic(0_f32, self.width as f32, self.height as f32, 0_f32, -1_f32, 1_f32);
+
             let push_values = PushValues {
-                projection: Matrix4::identity(),
+                projection: projection.transposed(),
                 transformation,
-                color: self.color.clone(),
+                color: [
+                    self.color.r as f32,
+                    self.color.g as f32,
+                    self.color.b as f32,
+                    self.color.a as f32,
+                ],
             };
 
             let draw_command = DrawCommand {
                 indices,
                 vertices,
                 push_values,
+                texture: self.pending_texture.take(),
+                shader: self.current_shader,
             };
 
             self.draw_commands.push(draw_command);
+        } else {
+            self.pending_texture = None;
         }
     }
 
     pub fn request_swapchain_recreation(&mut self, new_width: u32, new_height: u32) {
+        self.flush_batched_draws();
+
+        self.width = new_width;
+        self.height = new_height;
+
         self.backend.request_swapchain_recreation(new_width, new_height);
     }
 
@@ -210,15 +512,21 @@ impl<T: GraphicsBackend> Graphics<T> {
     pub fn rectangle(&mut self, x: f32, y: f32, w: f32, h: f32) {
         let start = self.vertices.len() as u32;
 
-        self.vertices.push(Vertex { position: [x, y] });
+        self.vertices.push(Vertex {
+            position: [x, y],
+            uv: [0_f32, 0_f32],
+        });
         self.vertices.push(Vertex {
             position: [x, y + h],
+            uv: [0_f32, 0_f32],
         });
         self.vertices.push(Vertex {
             position: [x + w, y],
+            uv: [0_f32, 0_f32],
         });
         self.vertices.push(Vertex {
             position: [x + w, y + h],
+            uv: [0_f32, 0_f32],
         });
 
         self.indices.push(start);
@@ -229,6 +537,280 @@ impl<T: GraphicsBackend> Graphics<T> {
         self.indices.push(start + 3);
     }
 
+    pub fn circle(&mut self, mode: DrawMode, x: f32, y: f32, r: f32) -> Result<(), TessellationError> {
+        let mut builder = Path::builder();
+        builder.add_circle(point(x, y), r, Winding::Positive);
+        let path = builder.build();
+
+        let geometry = self.tessellate(mode, &path)?;
+        self.append_geometry(geometry);
+        Ok(())
+    }
+
+    pub fn ellipse(
+        &mut self,
+        mode: DrawMode,
+        x: f32,
+        y: f32,
+        rx: f32,
+        ry: f32,
+    ) -> Result<(), TessellationError> {
+        let mut builder = Path::builder();
+        builder.add_ellipse(point(x, y), lyon_tessellation::math::vector(rx, ry), lyon_tessellation::math::Angle::zero(), Winding::Positive);
+        let path = builder.build();
+
+        let geometry = self.tessellate(mode, &path)?;
+        self.append_geometry(geometry);
+        Ok(())
+    }
+
+    pub fn polygon(&mut self, mode: DrawMode, points: &[f32]) -> Result<(), TessellationError> {
+        let points: Vec<Point> = points
+            .chunks_exact(2)
+            .map(|pair| point(pair[0], pair[1]))
+            .collect();
+
+        let mut builder = Path::builder();
+        builder.add_polygon(Polygon {
+            points: &points,
+            closed: true,
+        });
+        let path = builder.build();
+
+        let geometry = self.tessellate(mode, &path)?;
+        self.append_geometry(geometry);
+        Ok(())
+    }
+
+    pub fn line(&mut self, points: &[f32]) -> Result<(), TessellationError> {
+        let mut points = points.chunks_exact(2).map(|pair| point(pair[0], pair[1]));
+
+        let mut builder = Path::builder();
+        if let Some(first) = points.next() {
+            builder.begin(first);
+            for p in points {
+                builder.line_to(p);
+            }
+            builder.end(false);
+        }
+        let path = builder.build();
+
+        let geometry = self.tessellate_stroke(&path)?;
+        self.append_geometry(geometry);
+        Ok(())
+    }
+
+    pub fn arc(
+        &mut self,
+        mode: DrawMode,
+        x: f32,
+        y: f32,
+        r: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> Result<(), TessellationError> {
+        let arc = lyon_tessellation::geom::Arc {
+            center: point(x, y),
+            radii: lyon_tessellation::math::vector(r, r),
+            start_angle: lyon_tessellation::math::Angle::radians(start_angle),
+            sweep_angle: lyon_tessellation::math::Angle::radians(end_angle - start_angle),
+            x_rotation: lyon_tessellation::math::Angle::zero(),
+        };
+
+        let mut builder = Path::builder();
+        let mut started = false;
+        arc.for_each_quadratic_bezier(&mut |segment| {
+            if !started {
+                builder.begin(segment.from);
+                started = true;
+            }
+            builder.quadratic_bezier_to(segment.ctrl, segment.to);
+        });
+        if started {
+            builder.end(false);
+        }
+        let path = builder.build();
+
+        let geometry = self.tessellate(mode, &path)?;
+        self.append_geometry(geometry);
+        Ok(())
+    }
+
+    pub fn new_font(&mut self, bytes: Vec<u8>, size: f32) -> Result<FontHandle, ab_glyph::InvalidFont> {
+        self.fonts.push(Font::new(bytes, size)?);
+        Ok(FontHandle(self.fonts.len() - 1))
+    }
+
+    pub fn set_font(&mut self, font: FontHandle) {
+        self.current_font = Some(font);
+    }
+
+    fn sync_font_atlas(&mut self, font_index: usize) {
+        if !self.fonts[font_index].take_dirty() {
+            return;
+        }
+
+        let (width, height) = self.fonts[font_index].atlas_size();
+        let pixels = self.fonts[font_index].atlas_pixels().to_vec();
+
+        match self.font_textures.get(&font_index) {
+            Some(&(texture, tex_width, tex_height))
+                if tex_width == width && tex_height == height =>
+            {
+                self.backend.update_texture(texture, 0, 0, width, height, &pixels)
+            }
+            _ => {
+                // The atlas grew since the last upload (or this is the first
+                // upload): the existing texture is the wrong size, so a new
+                // one is created rather than resizing in place. The old
+                // texture (if any) is destroyed so growth doesn't leak one
+                // GPU texture per resize.
+                if let Some(&(old_texture, _, _)) = self.font_textures.get(&font_index) {
+                    self.backend.destroy_texture(old_texture);
+                }
+
+                let texture = self
+                    .backend
+                    .create_texture(width, height, TextureFormat::R8, &pixels);
+                self.font_textures.insert(font_index, (texture, width, height));
+            }
+        }
+    }
+
+    pub fn print(&mut self, text: &str, x: f32, y: f32) {
+        let font_index = match self.current_font {
+            Some(FontHandle(index)) => index,
+            None => return,
+        };
+
+        let (vertices, indices) = self.fonts[font_index].layout(text, x, y);
+        self.sync_font_atlas(font_index);
+        let (texture, _, _) = self.font_textures[&font_index];
+
+        self.flush_batched_draws();
+        self.vertices = vertices;
+        self.indices = indices;
+        self.pending_texture = Some(texture);
+        self.flush_batched_draws();
+    }
+
+    pub fn printf(&mut self, text: &str, x: f32, y: f32, wrap_width: f32) {
+        let font_index = match self.current_font {
+            Some(FontHandle(index)) => index,
+            None => return,
+        };
+
+        let line_height = self.fonts[font_index].line_height();
+        let lines = self.fonts[font_index].wrap(text, wrap_width);
+
+        for (i, line) in lines.iter().enumerate() {
+            self.print(line, x, y + line_height * i as f32);
+        }
+    }
+
+    pub fn new_image(&mut self, width: u32, height: u32, rgba: &[u8]) -> ImageHandle {
+        let texture = self
+            .backend
+            .create_texture(width, height, TextureFormat::Rgba8, rgba);
+
+        self.images.push((texture, width, height));
+        ImageHandle(self.images.len() - 1)
+    }
+
+    pub fn draw(&mut self, image: ImageHandle, x: f32, y: f32, r: f32, sx: f32, sy: f32) {
+        let ImageHandle(index) = image;
+        let (texture, width, height) = self.images[index];
+
+        let w = width as f32 * sx;
+        let h = height as f32 * sy;
+        let rotation = Matrix4::rotation(r);
+
+        let corners = [
+            ([0_f32, 0_f32], [0_f32, 0_f32]),
+            ([0_f32, h], [0_f32, 1_f32]),
+            ([w, 0_f32], [1_f32, 0_f32]),
+            ([w, h], [1_f32, 1_f32]),
+        ];
+
+        let vertices: Vec<Vertex> = corners
+            .into_iter()
+            .map(|([local_x, local_y], uv)| {
+                let rotated_x =
+                    rotation.get_value(0, 0) * local_x + rotation.get_value(0, 1) * local_y;
+                let rotated_y =
+                    rotation.get_value(1, 0) * local_x + rotation.get_value(1, 1) * local_y;
+
+                Vertex {
+                    position: [x + rotated_x, y + rotated_y],
+                    uv,
+                }
+            })
+            .collect();
+
+        self.flush_batched_draws();
+        self.vertices = vertices;
+        self.indices = vec![0, 1, 2, 2, 1, 3];
+        self.pending_texture = Some(texture);
+        self.flush_batched_draws();
+    }
+
+    pub fn new_shader_chunk(&mut self, name: &str, source: &str) {
+        self.shader_registry.register(name, source);
+    }
+
+    pub fn new_shader(
+        &mut self,
+        vertex_source: Option<String>,
+        fragment_source: String,
+    ) -> Result<ShaderHandle, PreprocessError> {
+        let vertex_source = vertex_source
+            .map(|source| self.shader_registry.preprocess(&source))
+            .transpose()?;
+        let fragment_source = self.shader_registry.preprocess(&fragment_source)?;
+
+        let handle = self
+            .backend
+            .create_shader(vertex_source.as_deref(), &fragment_source);
+
+        self.shader_uniforms.push((HashMap::new(), 0));
+
+        Ok(handle)
+    }
+
+    pub fn set_shader(&mut self, shader: Option<ShaderHandle>) {
+        self.flush_batched_draws();
+
+        self.current_shader = shader;
+    }
+
+    pub fn send(
+        &mut self,
+        shader: ShaderHandle,
+        name: &str,
+        value: UniformValue,
+    ) -> Result<(), UniformBufferOverflow> {
+        let ShaderHandle(index) = shader;
+        let (offsets, next_offset) = &mut self.shader_uniforms[index];
+
+        let offset = match offsets.get(name) {
+            Some(&offset) => offset,
+            None => {
+                let offset = *next_offset;
+                if offset + 16 > UNIFORM_BUFFER_SIZE {
+                    return Err(UniformBufferOverflow {
+                        name: name.to_string(),
+                    });
+                }
+                offsets.insert(name.to_string(), offset);
+                *next_offset += 16;
+                offset
+            }
+        };
+
+        self.backend.send_uniform(shader, offset, &value.to_bytes());
+        Ok(())
+    }
+
     pub fn present(&mut self) {
         self.flush_batched_draws();
 
@@ -4,44 +4,31 @@ use std::{
 };
 
 use rlua::{Function, Table};
-use vulkano::{
-    instance::{Instance, InstanceCreateInfo},
-    VulkanLibrary,
-};
-use vulkano_win::VkSurfaceBuild;
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
+mod font;
 mod graphics;
+mod shader;
 mod timer;
-mod vulkan_backend;
+mod wgpu_backend;
 
 fn run_lav() {
     let event_loop = EventLoop::new();
 
-    let library = VulkanLibrary::new().unwrap();
-    let required_extensions = vulkano_win::required_extensions(&library);
-
-    let instance = Instance::new(
-        library,
-        InstanceCreateInfo {
-            enabled_extensions: required_extensions,
-            enumerate_portability: true,
-            ..Default::default()
-        },
-    )
-    .unwrap();
-
-    let surface = WindowBuilder::new()
-        .build_vk_surface(&event_loop, instance.clone())
-        .unwrap();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let window_size = window.inner_size();
 
-    let backend = vulkan_backend::VulkanBackend::new(instance.clone(), surface.clone());
+    let backend = pollster::block_on(wgpu_backend::WgpuBackend::new(window));
 
-    let graphics = Arc::new(Mutex::new(graphics::Graphics::new(backend)));
+    let graphics = Arc::new(Mutex::new(graphics::Graphics::new(
+        backend,
+        window_size.width,
+        window_size.height,
+    )));
     let timer = Arc::new(Mutex::new(timer::Timer::new()));
 
     let lua = rlua::Lua::new();
@@ -102,6 +89,42 @@ fn run_lav() {
             })
             .unwrap();
 
+        let graphics_clone = graphics.clone();
+        let graphics_scale = ctx
+            .create_function_mut(move |_, (sx, sy)| {
+                graphics_clone.lock().unwrap().scale(sx, sy);
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_shear = ctx
+            .create_function_mut(move |_, (x, y)| {
+                graphics_clone.lock().unwrap().shear(x, y);
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_push = ctx
+            .create_function_mut(move |_, ()| {
+                graphics_clone.lock().unwrap().push();
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_pop = ctx
+            .create_function_mut(move |_, ()| {
+                graphics_clone.lock().unwrap().pop();
+
+                Ok(())
+            })
+            .unwrap();
+
         let graphics_clone = graphics.clone();
         let graphics_set_color = ctx
             .create_function_mut(move |_, (r, g, b, a)| {
@@ -114,6 +137,222 @@ fn run_lav() {
             })
             .unwrap();
 
+        let graphics_clone = graphics.clone();
+        let graphics_circle = ctx
+            .create_function_mut(move |_, (mode, x, y, r): (String, f32, f32, f32)| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .circle(graphics::DrawMode::from_str(&mode), x, y, r)
+                    .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_ellipse = ctx
+            .create_function_mut(move |_, (mode, x, y, rx, ry): (String, f32, f32, f32, f32)| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .ellipse(graphics::DrawMode::from_str(&mode), x, y, rx, ry)
+                    .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_polygon = ctx
+            .create_function_mut(move |_, (mode, points): (String, Vec<f32>)| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .polygon(graphics::DrawMode::from_str(&mode), &points)
+                    .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_line = ctx
+            .create_function_mut(move |_, points: Vec<f32>| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .line(&points)
+                    .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_arc = ctx
+            .create_function_mut(
+                move |_, (mode, x, y, r, start_angle, end_angle): (String, f32, f32, f32, f32, f32)| {
+                    graphics_clone
+                        .lock()
+                        .unwrap()
+                        .arc(
+                            graphics::DrawMode::from_str(&mode),
+                            x,
+                            y,
+                            r,
+                            start_angle,
+                            end_angle,
+                        )
+                        .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+                },
+            )
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_set_line_width = ctx
+            .create_function_mut(move |_, width| {
+                graphics_clone.lock().unwrap().set_line_width(width);
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_new_font = ctx
+            .create_function_mut(move |_, (path, size): (String, f32)| {
+                let bytes = fs::read(&path).map_err(|error| {
+                    rlua::Error::RuntimeError(format!("failed to read font \"{}\": {}", path, error))
+                })?;
+                let graphics::FontHandle(index) = graphics_clone
+                    .lock()
+                    .unwrap()
+                    .new_font(bytes, size)
+                    .map_err(|error| rlua::Error::RuntimeError(error.to_string()))?;
+
+                Ok(index)
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_set_font = ctx
+            .create_function_mut(move |_, index: usize| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .set_font(graphics::FontHandle(index));
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_print = ctx
+            .create_function_mut(move |_, (text, x, y): (String, f32, f32)| {
+                graphics_clone.lock().unwrap().print(&text, x, y);
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_printf = ctx
+            .create_function_mut(move |_, (text, x, y, wrap_width): (String, f32, f32, f32)| {
+                graphics_clone.lock().unwrap().printf(&text, x, y, wrap_width);
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_new_image = ctx
+            .create_function_mut(move |_, path: String| {
+                let decoded = image::open(&path)
+                    .map_err(|error| {
+                        rlua::Error::RuntimeError(format!(
+                            "failed to load image \"{}\": {}",
+                            path, error
+                        ))
+                    })?
+                    .to_rgba8();
+                let (width, height) = decoded.dimensions();
+                let graphics::ImageHandle(index) = graphics_clone
+                    .lock()
+                    .unwrap()
+                    .new_image(width, height, &decoded.into_raw());
+
+                Ok(index)
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_draw = ctx
+            .create_function_mut(
+                move |_, (index, x, y, r, sx, sy): (usize, f32, f32, f32, f32, f32)| {
+                    graphics_clone
+                        .lock()
+                        .unwrap()
+                        .draw(graphics::ImageHandle(index), x, y, r, sx, sy);
+
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_new_shader_chunk = ctx
+            .create_function_mut(move |_, (name, source): (String, String)| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .new_shader_chunk(&name, &source);
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_new_shader = ctx
+            .create_function_mut(
+                move |_, (fragment_source, vertex_source): (String, Option<String>)| {
+                    graphics_clone
+                        .lock()
+                        .unwrap()
+                        .new_shader(vertex_source, fragment_source)
+                        .map(|graphics::ShaderHandle(index)| index)
+                        .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+                },
+            )
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_set_shader = ctx
+            .create_function_mut(move |_, index: Option<usize>| {
+                graphics_clone
+                    .lock()
+                    .unwrap()
+                    .set_shader(index.map(graphics::ShaderHandle));
+
+                Ok(())
+            })
+            .unwrap();
+
+        let graphics_clone = graphics.clone();
+        let graphics_send = ctx
+            .create_function_mut(
+                move |_, (index, name, values): (usize, String, rlua::Variadic<f32>)| {
+                    let value = match values.as_slice() {
+                        &[x] => graphics::UniformValue::Float(x),
+                        &[x, y] => graphics::UniformValue::Vec2([x, y]),
+                        &[x, y, z, w] => graphics::UniformValue::Vec4([x, y, z, w]),
+                        _ => {
+                            return Err(rlua::Error::RuntimeError(
+                                "send() expects 1, 2 or 4 numbers".to_string(),
+                            ))
+                        }
+                    };
+
+                    graphics_clone
+                        .lock()
+                        .unwrap()
+                        .send(graphics::ShaderHandle(index), &name, value)
+                        .map_err(|error| rlua::Error::RuntimeError(error.to_string()))
+                },
+            )
+            .unwrap();
+
         let graphics_mod = ctx.create_table().unwrap();
 
         graphics_mod
@@ -124,7 +363,31 @@ fn run_lav() {
         graphics_mod.set("origin", graphics_origin).unwrap();
         graphics_mod.set("translate", graphics_translate).unwrap();
         graphics_mod.set("rotate", graphics_rotate).unwrap();
+        graphics_mod.set("scale", graphics_scale).unwrap();
+        graphics_mod.set("shear", graphics_shear).unwrap();
+        graphics_mod.set("push", graphics_push).unwrap();
+        graphics_mod.set("pop", graphics_pop).unwrap();
         graphics_mod.set("setColor", graphics_set_color).unwrap();
+        graphics_mod.set("circle", graphics_circle).unwrap();
+        graphics_mod.set("ellipse", graphics_ellipse).unwrap();
+        graphics_mod.set("polygon", graphics_polygon).unwrap();
+        graphics_mod.set("line", graphics_line).unwrap();
+        graphics_mod.set("arc", graphics_arc).unwrap();
+        graphics_mod
+            .set("setLineWidth", graphics_set_line_width)
+            .unwrap();
+        graphics_mod.set("newFont", graphics_new_font).unwrap();
+        graphics_mod.set("setFont", graphics_set_font).unwrap();
+        graphics_mod.set("print", graphics_print).unwrap();
+        graphics_mod.set("printf", graphics_printf).unwrap();
+        graphics_mod.set("newImage", graphics_new_image).unwrap();
+        graphics_mod.set("draw", graphics_draw).unwrap();
+        graphics_mod
+            .set("newShaderChunk", graphics_new_shader_chunk)
+            .unwrap();
+        graphics_mod.set("newShader", graphics_new_shader).unwrap();
+        graphics_mod.set("setShader", graphics_set_shader).unwrap();
+        graphics_mod.set("send", graphics_send).unwrap();
 
         lav.set("graphics", graphics_mod).unwrap();
 
@@ -198,10 +461,13 @@ fn run_lav() {
                 *control_flow = ControlFlow::Exit;
             }
             Event::WindowEvent {
-                event: WindowEvent::Resized(_),
+                event: WindowEvent::Resized(new_size),
                 ..
             } => {
-                graphics.lock().unwrap().request_swapchain_recreation();
+                graphics
+                    .lock()
+                    .unwrap()
+                    .request_swapchain_recreation(new_size.width, new_size.height);
             }
             Event::RedrawEventsCleared => {
                 lua.context(|ctx| {
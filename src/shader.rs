@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Raised while resolving `#include`/`#define` directives in a shader source.
+#[derive(Debug)]
+pub enum PreprocessError {
+    UnknownInclude(String),
+    IncludeCycle(String),
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreprocessError::UnknownInclude(name) => {
+                write!(f, "unknown shader include \"{}\"", name)
+            }
+            PreprocessError::IncludeCycle(name) => {
+                write!(f, "cyclic shader include of \"{}\"", name)
+            }
+        }
+    }
+}
+
+/// Named WGSL chunks that `#include "name"` directives resolve against.
+pub struct ShaderRegistry {
+    chunks: HashMap<String, String>,
+}
+
+impl ShaderRegistry {
+    pub fn new() -> ShaderRegistry {
+        ShaderRegistry {
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, source: &str) {
+        self.chunks.insert(name.to_string(), source.to_string());
+    }
+
+    /// Resolves `#include "name"` against the registry, then applies
+    /// `#define NAME value` as a textual substitution over the result.
+    pub fn preprocess(&self, source: &str) -> Result<String, PreprocessError> {
+        let mut stack = Vec::new();
+        let resolved = self.resolve_includes(source, &mut stack)?;
+        Ok(apply_defines(&resolved))
+    }
+
+    fn resolve_includes(
+        &self,
+        source: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<String, PreprocessError> {
+        let mut output = String::new();
+
+        for line in source.lines() {
+            let trimmed = line.trim();
+
+            if let Some(rest) = trimmed.strip_prefix("#include") {
+                let name = rest.trim().trim_matches('"').to_string();
+
+                if stack.iter().any(|included| *included == name) {
+                    return Err(PreprocessError::IncludeCycle(name));
+                }
+
+                let chunk = self
+                    .chunks
+                    .get(&name)
+                    .ok_or_else(|| PreprocessError::UnknownInclude(name.clone()))?
+                    .clone();
+
+                stack.push(name);
+                output.push_str(&self.resolve_includes(&chunk, stack)?);
+                stack.pop();
+                output.push('\n');
+            } else {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Is `b` a byte that can appear inside a WGSL identifier? Used to keep
+/// `#define` substitution from matching inside a longer identifier (e.g. a
+/// `#define R ...` must not touch `ROUGHNESS`).
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Replaces whole-word occurrences of `name` in `line` with `value`,
+/// leaving occurrences that are part of a longer identifier untouched.
+fn replace_identifier(line: &str, name: &str, value: &str) -> String {
+    let mut output = String::with_capacity(line.len());
+    let bytes = line.as_bytes();
+    let mut i = 0;
+
+    while i < line.len() {
+        let at_boundary_before = i
+            .checked_sub(1)
+            .map_or(true, |j| !is_ident_byte(bytes[j]));
+        let matches_here = line[i..].starts_with(name)
+            && bytes
+                .get(i + name.len())
+                .map_or(true, |&b| !is_ident_byte(b));
+
+        if at_boundary_before && matches_here {
+            output.push_str(value);
+            i += name.len();
+        } else {
+            let ch = line[i..].chars().next().unwrap();
+            output.push(ch);
+            i += ch.len_utf8();
+        }
+    }
+
+    output
+}
+
+fn apply_defines(source: &str) -> String {
+    // A `Vec` keeps defines in the order they were first declared, so
+    // chained/overlapping macros expand the same way on every run instead
+    // of depending on `HashMap` iteration order.
+    let mut defines: Vec<(String, String)> = Vec::new();
+    let mut output = String::new();
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+
+        if let Some(rest) = trimmed.strip_prefix("#define") {
+            let mut parts = rest.trim().splitn(2, char::is_whitespace);
+            if let Some(name) = parts.next() {
+                let value = parts.next().unwrap_or("").trim().to_string();
+                match defines.iter_mut().find(|(existing, _)| existing == name) {
+                    Some(entry) => entry.1 = value,
+                    None => defines.push((name.to_string(), value)),
+                }
+            }
+            continue;
+        }
+
+        let mut substituted = line.to_string();
+        for (name, value) in &defines {
+            substituted = replace_identifier(&substituted, name, value);
+        }
+        output.push_str(&substituted);
+        output.push('\n');
+    }
+
+    output
+}
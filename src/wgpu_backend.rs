@@ -1,7 +1,62 @@
+use wgpu::util::DeviceExt;
 use wgpu::{include_spirv, Backends};
 use winit::window::Window;
 
-use crate::graphics::{Color, GraphicsBackend};
+use crate::graphics::{
+    Color, DrawCommand, GraphicsBackend, PushValues, ShaderHandle, TextureFormat, TextureHandle,
+    Vertex,
+};
+
+/// Vertex shader used for custom fragment shaders that don't supply their
+/// own, matching the non-textured pipeline's vertex interface.
+const DEFAULT_SHADER_VERTEX_SOURCE: &str = r#"
+struct PushConstants {
+    projection: mat4x4<f32>,
+    transformation: mat4x4<f32>,
+    color: vec4<f32>,
+}
+var<push_constant> push_constants: PushConstants;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>, @location(1) uv: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = push_constants.projection * push_constants.transformation * vec4<f32>(position, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+"#;
+
+const VERTEX_BUFFER_LAYOUT: wgpu::VertexBufferLayout = wgpu::VertexBufferLayout {
+    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+};
+
+struct GpuTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    bytes_per_pixel: u32,
+}
+
+struct CustomShader {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+const SHADER_UNIFORM_BUFFER_SIZE: u64 = crate::graphics::UNIFORM_BUFFER_SIZE as u64;
+
+fn wgpu_format(format: TextureFormat) -> (wgpu::TextureFormat, u32) {
+    match format {
+        TextureFormat::R8 => (wgpu::TextureFormat::R8Unorm, 1),
+        TextureFormat::Rgba8 => (wgpu::TextureFormat::Rgba8UnormSrgb, 4),
+    }
+}
 
 pub struct WgpuBackend {
     window: Window,
@@ -9,6 +64,12 @@ pub struct WgpuBackend {
     queue: wgpu::Queue,
     surface: wgpu::Surface,
     render_pipeline: wgpu::RenderPipeline,
+    texture_pipeline: wgpu::RenderPipeline,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    textures: Vec<Option<GpuTexture>>,
+    shader_bind_group_layout: wgpu::BindGroupLayout,
+    shaders: Vec<CustomShader>,
     config: wgpu::SurfaceConfiguration,
     clear_color: Color,
 }
@@ -37,8 +98,11 @@ impl WgpuBackend {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                    features: wgpu::Features::PUSH_CONSTANTS,
+                    limits: wgpu::Limits {
+                        max_push_constant_size: std::mem::size_of::<PushValues>() as u32,
+                        ..wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits())
+                    },
                 },
                 None,
             )
@@ -49,12 +113,74 @@ impl WgpuBackend {
 
         let fragment_shader = device.create_shader_module(include_spirv!("shaders/frag.spv"));
 
+        let texture_vertex_shader =
+            device.create_shader_module(include_spirv!("shaders/vert_textured.spv"));
+
+        let texture_fragment_shader =
+            device.create_shader_module(include_spirv!("shaders/frag_textured.spv"));
+
+        let push_constant_ranges = [wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            range: 0..std::mem::size_of::<PushValues>() as u32,
+        }];
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[],
-            push_constant_ranges: &[],
+            push_constant_ranges: &push_constant_ranges,
+        });
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let texture_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&texture_bind_group_layout],
+                push_constant_ranges: &push_constant_ranges,
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
 
+        let shader_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
         let swapchain_capabilities = surface.get_capabilities(&adapter);
 
         let swapchain_format = swapchain_capabilities.formats[0];
@@ -65,7 +191,7 @@ impl WgpuBackend {
             vertex: wgpu::VertexState {
                 module: &vertex_shader,
                 entry_point: "main",
-                buffers: &[],
+                buffers: &[VERTEX_BUFFER_LAYOUT],
             },
             fragment: Some(wgpu::FragmentState {
                 module: &fragment_shader,
@@ -78,6 +204,25 @@ impl WgpuBackend {
             multiview: None,
         });
 
+        let texture_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&texture_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &texture_vertex_shader,
+                entry_point: "main",
+                buffers: &[VERTEX_BUFFER_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &texture_fragment_shader,
+                entry_point: "main",
+                targets: &[Some(swapchain_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
@@ -103,6 +248,12 @@ impl WgpuBackend {
             queue,
             surface,
             render_pipeline,
+            texture_pipeline,
+            texture_bind_group_layout,
+            sampler,
+            textures: Vec::new(),
+            shader_bind_group_layout,
+            shaders: Vec::new(),
             config,
             clear_color,
         }
@@ -124,7 +275,179 @@ impl GraphicsBackend for WgpuBackend {
         self.clear_color.a = a;
     }
 
-    fn present(&mut self, _draw_commands: Vec<crate::graphics::DrawCommand>) {
+    fn create_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        pixels: &[u8],
+    ) -> TextureHandle {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let (wgpu_format, bytes_per_pixel) = wgpu_format(format);
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu_format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        self.queue.write_texture(
+            texture.as_image_copy(),
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * bytes_per_pixel),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        self.textures.push(Some(GpuTexture {
+            texture,
+            bind_group,
+            bytes_per_pixel,
+        }));
+
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    fn destroy_texture(&mut self, texture: TextureHandle) {
+        self.textures[texture.0] = None;
+    }
+
+    fn update_texture(
+        &mut self,
+        texture: TextureHandle,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) {
+        let gpu_texture = self.textures[texture.0]
+            .as_ref()
+            .expect("update_texture called on a destroyed texture");
+
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &gpu_texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * gpu_texture.bytes_per_pixel),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    fn create_shader(&mut self, vertex_source: Option<&str>, fragment_source: &str) -> ShaderHandle {
+        let vertex_module = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(
+                vertex_source.unwrap_or(DEFAULT_SHADER_VERTEX_SOURCE).into(),
+            ),
+        });
+
+        let fragment_module = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(fragment_source.into()),
+        });
+
+        let uniform_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: SHADER_UNIFORM_BUFFER_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.shader_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&self.shader_bind_group_layout],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                range: 0..std::mem::size_of::<PushValues>() as u32,
+            }],
+        });
+
+        let pipeline = self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_module,
+                entry_point: "vs_main",
+                buffers: &[VERTEX_BUFFER_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_module,
+                entry_point: "fs_main",
+                targets: &[Some(self.config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        self.shaders.push(CustomShader {
+            pipeline,
+            uniform_buffer,
+            bind_group,
+        });
+
+        ShaderHandle(self.shaders.len() - 1)
+    }
+
+    fn send_uniform(&mut self, shader: ShaderHandle, offset: u32, bytes: &[u8]) {
+        self.queue
+            .write_buffer(&self.shaders[shader.0].uniform_buffer, offset as u64, bytes);
+    }
+
+    fn present(&mut self, draw_commands: Vec<DrawCommand>) {
         let frame = self
             .surface
             .get_current_texture()
@@ -134,6 +457,45 @@ impl GraphicsBackend for WgpuBackend {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        // Each draw command gets its own vertex/index buffer; the batch sizes
+        // are small and short-lived so there's no point pooling them yet.
+        let buffers: Vec<(
+            wgpu::Buffer,
+            wgpu::Buffer,
+            u32,
+            PushValues,
+            Option<TextureHandle>,
+            Option<ShaderHandle>,
+        )> = draw_commands
+            .iter()
+            .map(|command| {
+                let vertex_buffer =
+                    self.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: None,
+                            contents: bytemuck::cast_slice(&command.vertices),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        });
+
+                let index_buffer =
+                    self.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: None,
+                            contents: bytemuck::cast_slice(&command.indices),
+                            usage: wgpu::BufferUsages::INDEX,
+                        });
+
+                (
+                    vertex_buffer,
+                    index_buffer,
+                    command.indices.len() as u32,
+                    command.push_values,
+                    command.texture,
+                    command.shader,
+                )
+            })
+            .collect();
+
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -155,8 +517,34 @@ impl GraphicsBackend for WgpuBackend {
                 })],
                 depth_stencil_attachment: None,
             });
-            rpass.set_pipeline(&self.render_pipeline);
-            rpass.draw(0..3, 0..1);
+            for (vertex_buffer, index_buffer, index_count, push_values, texture, shader) in &buffers
+            {
+                match shader {
+                    Some(handle) => {
+                        rpass.set_pipeline(&self.shaders[handle.0].pipeline);
+                        rpass.set_bind_group(0, &self.shaders[handle.0].bind_group, &[]);
+                    }
+                    None => match texture {
+                        Some(handle) => {
+                            let gpu_texture = self.textures[handle.0]
+                                .as_ref()
+                                .expect("draw command references a destroyed texture");
+                            rpass.set_pipeline(&self.texture_pipeline);
+                            rpass.set_bind_group(0, &gpu_texture.bind_group, &[]);
+                        }
+                        None => rpass.set_pipeline(&self.render_pipeline),
+                    },
+                }
+
+                rpass.set_push_constants(
+                    wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    0,
+                    bytemuck::bytes_of(push_values),
+                );
+                rpass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                rpass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                rpass.draw_indexed(0..*index_count, 0, 0..1);
+            }
         }
 
         self.queue.submit(Some(encoder.finish()));